@@ -1,4 +1,5 @@
 use std::{
+    cmp::Ordering,
     ops::Deref,
     path::{Path, PathBuf},
 };
@@ -8,51 +9,125 @@ use nucleo_matcher::{
     Matcher,
 };
 use rayon::prelude::*;
-use vault::{MDHeading, MDIndexedBlock, Referenceable, Vault};
+use vault::{MDFile, MDHeading, MDIndexedBlock, Referenceable, Vault};
 
 pub(crate) struct Querier<'a> {
-    vault: &'a Vault,
+    /// Match strings for every named entity in the vault, built once on
+    /// construction instead of being rederived on every query.
+    index: Vec<MatchableNamedEntity<'a>>,
 }
 
 impl<'a> Querier<'a> {
     pub fn new(vault: &'a Vault) -> Self {
-        Self { vault }
-    }
-}
-
-impl<'a> Querier<'a> {
-    pub fn query(&self, link_query: LinkQuery) -> impl IndexedParallelIterator<Item = NamedEntity> {
-        let named_entities = self.get_named_entities();
-        let matchables = named_entities.map(MatchableNamedEntity::from);
-
-        let matched = fuzzy_match(
-            &link_query_string(link_query),
-            matchables.collect::<Vec<_>>().into_iter(),
-        );
-
-        matched.into_par_iter().map(|(it, _)| it.into())
+        let index = Self::build_index(vault);
+        Self { index }
     }
 
-    fn get_named_entities(&self) -> impl ParallelIterator<Item = NamedEntity<'a>> {
-        self.vault
+    fn build_index(vault: &'a Vault) -> Vec<MatchableNamedEntity<'a>> {
+        vault
             .select_referenceable_nodes(None)
             .into_par_iter()
             .flat_map(|it| match it {
-                Referenceable::File(path, _) => Some(NamedEntity(path, File)),
+                Referenceable::File(
+                    path,
+                    MDFile {
+                        title,
+                        aliases,
+                        tags,
+                        ..
+                    },
+                ) => Self::file_match_strings(path, *title, aliases)
+                    .into_iter()
+                    .map(|(match_string, alias)| {
+                        MatchableNamedEntity(match_string, NamedEntity(path, File(alias)), tags)
+                    })
+                    .collect::<Vec<_>>(),
                 Referenceable::Heading(
                     path,
                     MDHeading {
                         heading_text: data, ..
                     },
-                ) => Some(NamedEntity(path, Heading(data))),
+                ) => vec![MatchableNamedEntity::with_tags(
+                    NamedEntity(path, Heading(data)),
+                    vault.select_file_tags(path),
+                )],
                 Referenceable::IndexedBlock(path, MDIndexedBlock { index: data, .. }) => {
-                    Some(NamedEntity(path, IndexedBlock(data)))
+                    vec![MatchableNamedEntity::with_tags(
+                        NamedEntity(path, IndexedBlock(data)),
+                        vault.select_file_tags(path),
+                    )]
                 }
-                _ => None,
+                _ => vec![],
             })
+            .collect()
+    }
+
+    /// The filename always matches; an Obsidian-style `title` or `aliases`
+    /// frontmatter entry each contribute an additional match string that
+    /// resolves back to the same file, with the alias that was matched on
+    /// carried alongside so completion can render it.
+    fn file_match_strings(
+        path: &'a Path,
+        title: Option<&'a str>,
+        aliases: &'a [String],
+    ) -> Vec<(String, Option<&'a str>)> {
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let mut entries = vec![(file_name.to_string(), None)];
+
+        entries.extend(title.map(|title| (title.to_string(), Some(title))));
+        entries.extend(
+            aliases
+                .iter()
+                .map(|alias| (alias.clone(), Some(alias.as_str()))),
+        );
+
+        entries
     }
 }
 
+impl<'a> Querier<'a> {
+    pub fn query(
+        &self,
+        link_query: LinkQuery,
+        filter: &EntityFilter,
+    ) -> impl IndexedParallelIterator<Item = NamedEntity<'a>> {
+        let filter_text = link_query_string(link_query);
+
+        let candidates = self
+            .index
+            .iter()
+            .filter(|matchable| filter.allows(matchable));
+        let matched = fuzzy_match(&filter_text, candidates, &RankingRules::default());
+
+        // Resolve to owned entities (and drop the borrow on `self.index`)
+        // before deduping, so the returned iterator doesn't carry `&self`'s
+        // lifetime.
+        let matched: Vec<(NamedEntity<'a>, u32)> =
+            matched.map(|(it, score)| (it.into(), score)).collect();
+
+        dedupe_best_alias(matched)
+            .into_par_iter()
+            .map(|(entity, _)| entity)
+    }
+}
+
+/// A file can appear multiple times in `matched` (filename, title, each
+/// alias); the ranked order already puts the best-ranked match string
+/// first, so keeping the first [`NamedEntityInfo::File`] seen per path keeps
+/// the best-ranked alias and drops the rest. Within one typo tier that is
+/// not necessarily the one with the highest raw score. Headings/blocks are never
+/// deduped since each one is a distinct match target.
+fn dedupe_best_alias(matched: Vec<(NamedEntity, u32)>) -> Vec<(NamedEntity, u32)> {
+    let mut seen_files = std::collections::HashSet::new();
+    matched
+        .into_iter()
+        .filter(|(entity, _)| match entity.1 {
+            File(_) => seen_files.insert(entity.0),
+            _ => true,
+        })
+        .collect()
+}
+
 fn link_query_string(link_query: LinkQuery) -> String {
     match link_query {
         LinkQuery {
@@ -70,30 +145,103 @@ fn link_query_string(link_query: LinkQuery) -> String {
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct NamedEntity<'a>(pub &'a Path, pub NamedEntityInfo<'a>);
 
 use NamedEntityInfo::*;
 
 use crate::parser::{InfileRef, LinkQuery};
+#[derive(Clone, Copy)]
 pub enum NamedEntityInfo<'a> {
-    File,
+    /// `Some(alias)` when this file was matched via its `title` or an
+    /// `aliases` entry rather than its filename.
+    File(Option<&'a str>),
     Heading(&'a str),
     IndexedBlock(&'a str),
 }
 
-struct MatchableNamedEntity<'a>(String, NamedEntity<'a>);
+/// Restricts a [`Querier::query`] call to a subset of named entities,
+/// applied before fuzzy matching so scoped pickers (e.g. "headings only,
+/// under `notes/daily`") don't waste time scoring candidates the caller
+/// can't want.
+#[derive(Default, Clone)]
+pub struct EntityFilter {
+    /// Only entities of these kinds are considered; `None` means no
+    /// restriction.
+    pub kinds: Option<EntityKindMask>,
+    /// Only files whose path starts with this prefix (and their headings
+    /// and blocks) are considered.
+    pub path_prefix: Option<PathBuf>,
+    /// Only files carrying this tag (and their headings and blocks) are
+    /// considered.
+    pub tag: Option<String>,
+}
+
+impl EntityFilter {
+    fn allows(&self, matchable: &MatchableNamedEntity) -> bool {
+        let NamedEntity(path, info) = matchable.1;
+
+        if let Some(kinds) = &self.kinds {
+            if !kinds.allows(&info) {
+                return false;
+            }
+        }
+
+        if let Some(prefix) = &self.path_prefix {
+            if !path.starts_with(prefix) {
+                return false;
+            }
+        }
 
-impl<'a> From<NamedEntity<'a>> for MatchableNamedEntity<'a> {
-    fn from(value: NamedEntity<'a>) -> Self {
-        let file_ref = value.0.file_name().unwrap().to_str().unwrap();
+        if let Some(tag) = &self.tag {
+            if !matchable.2.iter().any(|entity_tag| entity_tag == tag) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
 
-        let match_string = match value.1 {
+/// Which [`NamedEntityInfo`] variants an [`EntityFilter`] lets through.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EntityKindMask {
+    pub file: bool,
+    pub heading: bool,
+    pub indexed_block: bool,
+}
+
+impl EntityKindMask {
+    pub const ALL: Self = Self {
+        file: true,
+        heading: true,
+        indexed_block: true,
+    };
+
+    fn allows(&self, info: &NamedEntityInfo) -> bool {
+        match info {
+            File(_) => self.file,
+            Heading(_) => self.heading,
+            IndexedBlock(_) => self.indexed_block,
+        }
+    }
+}
+
+/// A named entity's match string alongside the tags of the file it belongs
+/// to, so [`EntityFilter`] can scope by tag without a second vault lookup.
+struct MatchableNamedEntity<'a>(String, NamedEntity<'a>, &'a [String]);
+
+impl<'a> MatchableNamedEntity<'a> {
+    fn with_tags(entity: NamedEntity<'a>, tags: &'a [String]) -> Self {
+        let file_ref = entity.0.file_name().unwrap().to_str().unwrap();
+
+        let match_string = match entity.1 {
             Heading(heading) => format!("{file_ref}#{heading}"),
             IndexedBlock(index) => format!("{file_ref}#^{index}"),
             _ => file_ref.to_string(),
         };
 
-        MatchableNamedEntity(match_string, value)
+        MatchableNamedEntity(match_string, entity, tags)
     }
 }
 
@@ -103,6 +251,12 @@ impl<'a> From<MatchableNamedEntity<'a>> for NamedEntity<'a> {
     }
 }
 
+impl<'a> From<&MatchableNamedEntity<'a>> for NamedEntity<'a> {
+    fn from(value: &MatchableNamedEntity<'a>) -> Self {
+        value.1
+    }
+}
+
 impl<'a> Deref for MatchableNamedEntity<'a> {
     type Target = NamedEntity<'a>;
     fn deref(&self) -> &Self::Target {
@@ -114,6 +268,28 @@ impl Matchable for MatchableNamedEntity<'_> {
     fn match_string(&self) -> &str {
         &self.0
     }
+
+    fn attribute_weight(&self, filter_text: &str) -> u8 {
+        // A query that explicitly asks for an in-file ref (`#heading` /
+        // `#^block`) shouldn't be penalized for matching one; otherwise
+        // whole-file matches should surface above them.
+        if filter_text.contains('#') {
+            return 0;
+        }
+
+        match self.1 .1 {
+            File(_) => 0,
+            Heading(_) | IndexedBlock(_) => 1,
+        }
+    }
+
+    fn match_string_is_filename(&self) -> bool {
+        // `File(Some(alias))` matches were built from a title/alias, which
+        // unlike a filename has no extension to strip; everything else
+        // (the bare filename, and headings/blocks, which are always
+        // prefixed with the raw filename) does.
+        !matches!(self.1 .1, File(Some(_)))
+    }
 }
 
 impl<'a> Matchable for (String, &'a PathBuf) {
@@ -124,6 +300,37 @@ impl<'a> Matchable for (String, &'a PathBuf) {
 
 pub trait Matchable {
     fn match_string(&self) -> &str;
+
+    /// Lower-ranked attributes are preferred when two candidates are
+    /// otherwise tied. Used by [`RankingRules`] to, e.g., rank whole-file
+    /// matches above headings/blocks when the query doesn't target one.
+    fn attribute_weight(&self, filter_text: &str) -> u8 {
+        let _ = filter_text;
+        0
+    }
+
+    /// Whether the part of `match_string` before any `#` is a raw filename
+    /// (and so carries an extension that [`exactness_rank`] should ignore),
+    /// as opposed to an alias/title, which has no extension to strip.
+    /// Defaults to `true`, matching every `Matchable` impl except
+    /// alias/title file matches.
+    fn match_string_is_filename(&self) -> bool {
+        true
+    }
+}
+
+impl<T: Matchable + ?Sized> Matchable for &T {
+    fn match_string(&self) -> &str {
+        (**self).match_string()
+    }
+
+    fn attribute_weight(&self, filter_text: &str) -> u8 {
+        (**self).attribute_weight(filter_text)
+    }
+
+    fn match_string_is_filename(&self) -> bool {
+        (**self).match_string_is_filename()
+    }
 }
 
 struct NucleoMatchable<T: Matchable>(T);
@@ -140,21 +347,265 @@ impl<T: Matchable> AsRef<str> for NucleoMatchable<T> {
     }
 }
 
-// TODO: parallelize this
+/// A composable, MeiliSearch-style ranking-rule pipeline applied to the
+/// output of [`fuzzy_match`].
+///
+/// Results are first bucketed into "typo tiers" by their raw nucleo score
+/// (so a badly-typo'd match never outranks a close one), then within a tier
+/// ordered by, in turn: exactness, proximity of the matched characters, and
+/// attribute weight. `tie_breaker` is consulted last, for callers that want
+/// to break remaining ties themselves (e.g. by recency or frontmatter
+/// order).
+pub struct RankingRules<T> {
+    /// Width of a score bucket: two candidates whose nucleo scores fall in
+    /// the same `score / typo_tier_width` bucket are considered the same
+    /// typo tier and re-ordered by the rules below instead of raw score.
+    pub typo_tier_width: u32,
+    pub tie_breaker: Option<fn(&T, &T) -> Ordering>,
+}
+
+impl<T> Default for RankingRules<T> {
+    fn default() -> Self {
+        Self {
+            typo_tier_width: 8,
+            tie_breaker: None,
+        }
+    }
+}
+
+/// Match strings derived from a filename are built from the full filename
+/// (`file.md#heading`), but a query never includes the extension
+/// (`file#heading`); alias/title match strings have no extension at all
+/// (e.g. `v2.0 spec`) and must be compared as-is. Split both the candidate
+/// and the query into `(file_ref, in_file_ref)`, drop the extension from
+/// `file_ref` only when [`Matchable::match_string_is_filename`] says it's
+/// actually a filename, then compare; mismatched `in_file_ref`s (a query
+/// targeting a heading/block against a candidate that isn't one, or vice
+/// versa) can never be exact/prefix matches of each other.
+fn exactness_rank<T: Matchable>(filter_text: &str, item: &T) -> u8 {
+    let (file_ref, in_file_ref) = split_file_ref(item.match_string());
+    let file_ref = if item.match_string_is_filename() {
+        file_ref.rsplit_once('.').map_or(file_ref, |(stem, _)| stem)
+    } else {
+        file_ref
+    };
+
+    let (filter_file_ref, filter_in_file_ref) = split_file_ref(filter_text);
+
+    if in_file_ref != filter_in_file_ref {
+        return 2;
+    }
+
+    if filter_file_ref.eq_ignore_ascii_case(file_ref) {
+        0
+    } else if file_ref.is_char_boundary(filter_file_ref.len())
+        && file_ref[..filter_file_ref.len()].eq_ignore_ascii_case(filter_file_ref)
+    {
+        1
+    } else {
+        2
+    }
+}
+
+/// Splits `file_ref#in_file_ref` into its two parts; `in_file_ref` is `None`
+/// when there's no `#`.
+fn split_file_ref(s: &str) -> (&str, Option<&str>) {
+    match s.split_once('#') {
+        Some((file_ref, in_file_ref)) => (file_ref, Some(in_file_ref)),
+        None => (s, None),
+    }
+}
+
+/// Span between the first and last matched character, the proximity signal
+/// nucleo's raw indices expose; smaller spans indicate a tighter match.
+/// `Pattern::indices` appends per matched atom rather than guaranteeing
+/// ascending order (a multi-word query like `My Daily Note` matches more
+/// than one atom), so take the min/max rather than assuming the first and
+/// last entries are the extremes.
+fn proximity(indices: &[u32]) -> u32 {
+    match (indices.iter().min(), indices.iter().max()) {
+        (Some(min), Some(max)) => max - min,
+        _ => 0,
+    }
+}
+
+fn rank_matches<T: Matchable>(
+    filter_text: &str,
+    mut matches: Vec<(T, u32, Vec<u32>)>,
+    rules: &RankingRules<T>,
+) -> Vec<(T, u32)> {
+    matches.sort_by(|(a, a_score, a_indices), (b, b_score, b_indices)| {
+        let tier_a = a_score / rules.typo_tier_width.max(1);
+        let tier_b = b_score / rules.typo_tier_width.max(1);
+
+        tier_b
+            .cmp(&tier_a)
+            .then_with(|| exactness_rank(filter_text, a).cmp(&exactness_rank(filter_text, b)))
+            .then_with(|| proximity(a_indices).cmp(&proximity(b_indices)))
+            .then_with(|| {
+                a.attribute_weight(filter_text)
+                    .cmp(&b.attribute_weight(filter_text))
+            })
+            .then_with(|| match rules.tie_breaker {
+                Some(tie_breaker) => tie_breaker(a, b),
+                None => Ordering::Equal,
+            })
+    });
+
+    matches
+        .into_iter()
+        .map(|(item, score, _)| (item, score))
+        .collect()
+}
+
 pub fn fuzzy_match<'a, T: Matchable + Send>(
     filter_text: &str,
     items: impl Iterator<Item = T>,
+    rules: &RankingRules<T>,
 ) -> impl IndexedParallelIterator<Item = (T, u32)> {
-    let items = items.map(NucleoMatchable);
+    let items: Vec<_> = items.map(NucleoMatchable).collect();
 
-    let mut matcher = Matcher::new(nucleo_matcher::Config::DEFAULT);
-
-    let matches = pattern::Pattern::parse(
+    let pattern = pattern::Pattern::parse(
         filter_text,
         pattern::CaseMatching::Smart,
         Normalization::Smart,
-    )
-    .match_list(items, &mut matcher);
+    );
+
+    // `Matcher` isn't `Sync`, so each rayon worker gets its own via
+    // `map_init` rather than sharing one across threads; every item is
+    // still scored independently of thread scheduling, so results and
+    // scores don't depend on how items are split across threads.
+    let matches: Vec<_> = items
+        .into_par_iter()
+        .map_init(
+            || Matcher::new(nucleo_matcher::Config::DEFAULT),
+            |matcher, item| {
+                let mut haystack_buf = Vec::new();
+                let haystack = nucleo_matcher::Utf32Str::new(item.as_ref(), &mut haystack_buf);
+
+                let mut indices = Vec::new();
+                let score = pattern.indices(haystack, matcher, &mut indices);
+
+                score.map(|score| (item.0, score, indices))
+            },
+        )
+        .flatten_iter()
+        .collect();
+
+    rank_matches(filter_text, matches, rules).into_par_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    matches.into_par_iter().map(|(item, score)| (item.0, score))
-}
\ No newline at end of file
+    #[test]
+    fn exactness_rank_strips_extension_for_filenames() {
+        let path = Path::new("note.md");
+        let entity =
+            MatchableNamedEntity("note.md".to_string(), NamedEntity(path, File(None)), &[]);
+
+        assert_eq!(exactness_rank("note", &entity), 0);
+    }
+
+    #[test]
+    fn exactness_rank_keeps_dots_in_aliases() {
+        let path = Path::new("note.md");
+        let alias = "v2.0 spec";
+        let entity =
+            MatchableNamedEntity(alias.to_string(), NamedEntity(path, File(Some(alias))), &[]);
+
+        // If the extension were stripped here as it is for filenames, this
+        // would be truncated to "v2" and miss the exact match.
+        assert_eq!(exactness_rank("v2.0 spec", &entity), 0);
+    }
+
+    #[test]
+    fn exactness_rank_ignores_extension_only_for_filenames_not_titles() {
+        let path = Path::new("note.md");
+        let filename =
+            MatchableNamedEntity("note.md".to_string(), NamedEntity(path, File(None)), &[]);
+        let title = MatchableNamedEntity(
+            "note.md".to_string(),
+            NamedEntity(path, File(Some("note.md"))),
+            &[],
+        );
+
+        assert_eq!(exactness_rank("note", &filename), 0);
+        assert_eq!(exactness_rank("note", &title), 2);
+    }
+
+    #[test]
+    fn proximity_is_robust_to_out_of_order_indices() {
+        // `Pattern::indices` appends per matched atom, so a multi-word query
+        // can yield indices where the last entry isn't the largest.
+        assert_eq!(proximity(&[5, 1, 3]), 4);
+        assert_eq!(proximity(&[1, 5, 3]), 4);
+        assert_eq!(proximity(&[]), 0);
+    }
+
+    #[test]
+    fn dedupe_best_alias_keeps_first_occurrence_per_file() {
+        let path = Path::new("note.md");
+        let alias_entity = NamedEntity(path, File(Some("Nickname")));
+        let filename_entity = NamedEntity(path, File(None));
+
+        // Ranked order already puts the best-scoring match first.
+        let matched = vec![(alias_entity, 100), (filename_entity, 50)];
+        let deduped = dedupe_best_alias(matched);
+
+        assert_eq!(deduped.len(), 1);
+        match deduped[0].0 .1 {
+            File(Some(alias)) => assert_eq!(alias, "Nickname"),
+            _ => panic!("expected the higher-ranked alias match to survive dedup"),
+        }
+    }
+
+    #[test]
+    fn dedupe_best_alias_keeps_distinct_headings() {
+        let path = Path::new("note.md");
+        let first_heading = NamedEntity(path, Heading("intro"));
+        let second_heading = NamedEntity(path, Heading("outro"));
+
+        let matched = vec![(first_heading, 80), (second_heading, 60)];
+        let deduped = dedupe_best_alias(matched);
+
+        assert_eq!(deduped.len(), 2);
+    }
+
+    #[test]
+    fn entity_filter_allows_scopes_by_kind_path_and_tag() {
+        let path = Path::new("notes/daily/today.md");
+        let tags = vec!["journal".to_string()];
+        let entity =
+            MatchableNamedEntity("today.md".to_string(), NamedEntity(path, File(None)), &tags);
+
+        let headings_only = EntityFilter {
+            kinds: Some(EntityKindMask {
+                file: false,
+                heading: true,
+                indexed_block: true,
+            }),
+            ..Default::default()
+        };
+        assert!(!headings_only.allows(&entity));
+
+        let wrong_folder = EntityFilter {
+            path_prefix: Some(PathBuf::from("notes/other")),
+            ..Default::default()
+        };
+        assert!(!wrong_folder.allows(&entity));
+
+        let matching_tag = EntityFilter {
+            tag: Some("journal".to_string()),
+            ..Default::default()
+        };
+        assert!(matching_tag.allows(&entity));
+
+        let missing_tag = EntityFilter {
+            tag: Some("work".to_string()),
+            ..Default::default()
+        };
+        assert!(!missing_tag.allows(&entity));
+    }
+}